@@ -1,11 +1,18 @@
 use pyo3::{pymodule, PyResult, Python};
 use pyo3::types::PyModule;
 
+mod openmetrics;
+mod pstats_export;
+mod sampling_profiler;
 mod stats_aggregator;
+mod tdigest;
+mod window;
+use sampling_profiler::PySamplingProfiler;
 use stats_aggregator::PyAggregatedStats;
 
 #[pymodule]
 fn fastapi_profiler_rust(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyAggregatedStats>()?;
+    m.add_class::<PySamplingProfiler>()?;
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,141 @@
+//! Prometheus / OpenMetrics text exposition of aggregated endpoint stats.
+//!
+//! The rendering lives here, in Rust, so a `/metrics` scrape doesn't rebuild
+//! the payload with Python string concatenation on every request. The output
+//! follows the Prometheus text exposition format: a
+//! `http_request_duration_seconds` summary (quantile lines plus `_count` and
+//! `_sum`) and an `http_requests_total` counter labelled by `method`, `path`
+//! and `status`.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use crate::tdigest::TDigest;
+
+/// Quantiles published in the duration summary.
+const QUANTILES: [f64; 4] = [0.5, 0.9, 0.95, 0.99];
+
+/// Latency and request counts for a single `(method, path)` endpoint.
+#[derive(Default)]
+pub struct EndpointStats {
+    pub count: u64,
+    pub sum: f64,
+    pub digest: TDigest,
+    /// Request count broken down by HTTP status code.
+    pub status_counts: HashMap<u16, u64>,
+}
+
+/// Render the endpoint table as Prometheus text exposition format.
+pub fn render(endpoints: &HashMap<(String, String), EndpointStats>) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP http_request_duration_seconds Request latency in seconds.\n");
+    out.push_str("# TYPE http_request_duration_seconds summary\n");
+    for ((method, path), stats) in endpoints {
+        let labels = format!(
+            "method=\"{}\",path=\"{}\"",
+            escape(method),
+            escape(path)
+        );
+        for q in QUANTILES {
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds{{{labels},quantile=\"{q}\"}} {}",
+                fmt_float(stats.digest.quantile(q))
+            );
+        }
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_count{{{labels}}} {}",
+            stats.count
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_sum{{{labels}}} {}",
+            fmt_float(stats.sum)
+        );
+    }
+
+    out.push_str("# HELP http_requests_total Total HTTP requests.\n");
+    out.push_str("# TYPE http_requests_total counter\n");
+    for ((method, path), stats) in endpoints {
+        for (status, count) in &stats.status_counts {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",path=\"{}\",status=\"{}\"}} {}",
+                escape(method),
+                escape(path),
+                status,
+                count
+            );
+        }
+    }
+
+    out
+}
+
+/// Escape a label value per the exposition format: backslash, double-quote and
+/// newline are the only characters that must be escaped.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Format a float, rendering a non-finite estimate (empty digest) as `NaN`,
+/// which Prometheus accepts.
+fn fmt_float(v: f64) -> String {
+    if v.is_finite() {
+        format!("{v}")
+    } else {
+        "NaN".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_summary_counter_and_escapes_labels() {
+        let mut ep = EndpointStats::default();
+        // A constant distribution makes every quantile exactly 0.1.
+        for _ in 0..10 {
+            ep.count += 1;
+            ep.sum += 0.1;
+            ep.digest.record(0.1);
+        }
+        ep.status_counts.insert(200, 7);
+        ep.status_counts.insert(500, 3);
+
+        let mut endpoints = HashMap::new();
+        // A quote in the path exercises label escaping.
+        endpoints.insert(("GET".to_string(), "/a\"b".to_string()), ep);
+
+        let out = render(&endpoints);
+
+        assert!(out.contains("# TYPE http_request_duration_seconds summary\n"));
+        assert!(out.contains(
+            "http_request_duration_seconds{method=\"GET\",path=\"/a\\\"b\",quantile=\"0.99\"} 0.1\n"
+        ));
+        assert!(out.contains("http_request_duration_seconds_count{method=\"GET\",path=\"/a\\\"b\"} 10\n"));
+        // Ten additions of 0.1 sum to 0.9999999999999999 in f64, not 1.
+        assert!(out.contains(
+            "http_request_duration_seconds_sum{method=\"GET\",path=\"/a\\\"b\"} 0.9999999999999999\n"
+        ));
+        assert!(out.contains("# TYPE http_requests_total counter\n"));
+        assert!(out.contains(
+            "http_requests_total{method=\"GET\",path=\"/a\\\"b\",status=\"200\"} 7\n"
+        ));
+        assert!(out.contains(
+            "http_requests_total{method=\"GET\",path=\"/a\\\"b\",status=\"500\"} 3\n"
+        ));
+    }
+}
@@ -0,0 +1,232 @@
+//! cProfile/`pstats` compatible export of collected per-call data.
+//!
+//! The canonical `pstats` on-disk format is simply `marshal.dump` of a mapping
+//!
+//! ```text
+//! {func: (cc, nc, tt, ct, callers)}
+//! ```
+//!
+//! where `func` is a `(filename, lineno, funcname)` tuple, `cc`/`nc` are the
+//! primitive and total call counts, `tt`/`ct` the internal and cumulative
+//! times, and `callers` the same shape keyed by the calling function. We build
+//! that mapping as native Python objects and hand it to the stdlib `marshal`
+//! module so the resulting `.prof` loads in snakeviz or `pstats.Stats`
+//! unchanged — there is no point reimplementing marshal's byte layout in Rust.
+
+use std::collections::HashMap;
+
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyTuple};
+
+/// A `(filename, lineno, funcname)` identity, matching the `pstats` func tuple.
+pub type FuncKey = (String, i64, String);
+
+/// Accumulated statistics for a single function.
+#[derive(Clone, Default)]
+pub struct FuncRecord {
+    /// Primitive (non-recursive) call count.
+    pub cc: i64,
+    /// Total call count.
+    pub nc: i64,
+    /// Time spent in the function itself.
+    pub tt: f64,
+    /// Cumulative time including callees.
+    pub ct: f64,
+    /// Per-caller breakdown: caller -> (cc, nc, tt, ct).
+    pub callers: HashMap<FuncKey, (i64, i64, f64, f64)>,
+}
+
+/// Column a `pstats` table can be sorted by, mirroring `Stats.sort_stats`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Cumulative,
+    TotTime,
+    NCalls,
+    PerCall,
+}
+
+impl SortKey {
+    /// Parse the textual column name used by the Python-facing API.
+    pub fn parse(name: &str) -> Option<SortKey> {
+        match name {
+            "cumulative" | "cumtime" => Some(SortKey::Cumulative),
+            "tottime" => Some(SortKey::TotTime),
+            "ncalls" | "calls" => Some(SortKey::NCalls),
+            "percall" => Some(SortKey::PerCall),
+            _ => None,
+        }
+    }
+}
+
+/// Render a `func -> record` mapping as a sorted textual table.
+///
+/// `restrict` keeps only the top-N rows after sorting (descending); `None`
+/// keeps them all.
+pub fn format_table(
+    records: &HashMap<FuncKey, FuncRecord>,
+    sort: SortKey,
+    restrict: Option<usize>,
+) -> String {
+    let mut rows: Vec<(&FuncKey, &FuncRecord)> = records.iter().collect();
+    rows.sort_by(|(_, a), (_, b)| {
+        let (ka, kb) = (sort_value(a, sort), sort_value(b, sort));
+        kb.partial_cmp(&ka).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    if let Some(n) = restrict {
+        rows.truncate(n);
+    }
+
+    let mut out = String::new();
+    out.push_str("   ncalls  tottime  percall  cumtime  percall  filename:lineno(function)\n");
+    for (key, rec) in rows {
+        let pc_tt = if rec.nc != 0 { rec.tt / rec.nc as f64 } else { 0.0 };
+        let pc_ct = if rec.cc != 0 { rec.ct / rec.cc as f64 } else { 0.0 };
+        let ncalls = if rec.cc != rec.nc {
+            format!("{}/{}", rec.nc, rec.cc)
+        } else {
+            rec.nc.to_string()
+        };
+        out.push_str(&format!(
+            "{:>9}  {:>7.4}  {:>7.4}  {:>7.4}  {:>7.4}  {}:{}({})\n",
+            ncalls, rec.tt, pc_tt, rec.ct, pc_ct, key.0, key.1, key.2
+        ));
+    }
+    out
+}
+
+fn sort_value(rec: &FuncRecord, sort: SortKey) -> f64 {
+    match sort {
+        SortKey::Cumulative => rec.ct,
+        SortKey::TotTime => rec.tt,
+        SortKey::NCalls => rec.nc as f64,
+        SortKey::PerCall => {
+            if rec.nc != 0 {
+                rec.tt / rec.nc as f64
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+/// Marshal a `func -> record` mapping into the `pstats` binary layout, returning
+/// the raw bytes ready to be written to a `.prof` file.
+pub fn marshal_stats<'py>(
+    py: Python<'py>,
+    records: &HashMap<FuncKey, FuncRecord>,
+) -> PyResult<&'py PyBytes> {
+    let stats = PyDict::new(py);
+    for (key, rec) in records {
+        let callers = PyDict::new(py);
+        for (ck, &(cc, nc, tt, ct)) in &rec.callers {
+            callers.set_item(func_tuple(py, ck), (cc, nc, tt, ct))?;
+        }
+        let value = PyTuple::new(py, [
+            rec.cc.into_py(py),
+            rec.nc.into_py(py),
+            rec.tt.into_py(py),
+            rec.ct.into_py(py),
+            callers.into_py(py),
+        ]);
+        stats.set_item(func_tuple(py, key), value)?;
+    }
+
+    let marshal = py.import("marshal")?;
+    let bytes = marshal.call_method1("dumps", (stats,))?;
+    bytes.extract()
+}
+
+fn func_tuple<'py>(py: Python<'py>, key: &FuncKey) -> &'py PyTuple {
+    PyTuple::new(py, [key.0.clone().into_py(py), key.1.into_py(py), key.2.clone().into_py(py)])
+}
+
+/// Expand a `filename_format` template containing `{method}`, `{path}`,
+/// `{elapsed}` and `{time}` fields into a concrete file name.
+///
+/// `path` is sanitised so it is safe to use as a single filename component.
+pub fn expand_filename(
+    template: &str,
+    method: &str,
+    path: &str,
+    elapsed: f64,
+    time: f64,
+) -> String {
+    let safe_path: String = path
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    template
+        .replace("{method}", method)
+        .replace("{path}", safe_path.trim_matches('_'))
+        .replace("{elapsed}", &format!("{:.6}", elapsed))
+        .replace("{time}", &format!("{:.0}", time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(cc: i64, nc: i64, tt: f64, ct: f64) -> FuncRecord {
+        FuncRecord { cc, nc, tt, ct, callers: HashMap::new() }
+    }
+
+    fn sample() -> HashMap<FuncKey, FuncRecord> {
+        let mut m = HashMap::new();
+        m.insert(("a.py".into(), 1, "slow".into()), rec(1, 1, 0.1, 9.0));
+        m.insert(("b.py".into(), 2, "busy".into()), rec(1, 1, 5.0, 6.0));
+        m.insert(("c.py".into(), 3, "quick".into()), rec(1, 1, 0.01, 0.02));
+        m
+    }
+
+    #[test]
+    fn table_sorts_by_column_and_restricts() {
+        let m = sample();
+        // By cumulative time, "slow" (ct 9.0) ranks first.
+        let cumulative = format_table(&m, SortKey::Cumulative, Some(1));
+        let rows: Vec<&str> = cumulative.lines().skip(1).collect();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].contains("a.py:1(slow)"));
+
+        // By total time, "busy" (tt 5.0) ranks first instead.
+        let tottime = format_table(&m, SortKey::TotTime, Some(1));
+        let rows: Vec<&str> = tottime.lines().skip(1).collect();
+        assert!(rows[0].contains("b.py:2(busy)"));
+    }
+
+    #[test]
+    fn filename_template_expands_and_sanitises_path() {
+        let name = expand_filename("{method}_{path}_{time}.prof", "GET", "/api/v1/users", 0.25, 1700.0);
+        assert_eq!(name, "GET_api_v1_users_1700.prof");
+    }
+
+    #[test]
+    fn marshal_round_trips_as_pstats_mapping() {
+        let mut m = HashMap::new();
+        let mut callers = HashMap::new();
+        callers.insert(("caller.py".to_string(), 9, "parent".to_string()), (1, 1, 0.5, 0.5));
+        m.insert(
+            ("a.py".to_string(), 1, "slow".to_string()),
+            FuncRecord { cc: 1, nc: 2, tt: 0.1, ct: 9.0, callers },
+        );
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let bytes = marshal_stats(py, &m).unwrap();
+            let marshal = py.import("marshal").unwrap();
+            let loaded = marshal.call_method1("loads", (bytes,)).unwrap();
+            let stats: &pyo3::types::PyDict = loaded.downcast().unwrap();
+
+            let key = ("a.py", 1, "slow");
+            let value: (i64, i64, f64, f64, &pyo3::types::PyDict) =
+                stats.get_item(key).unwrap().unwrap().extract().unwrap();
+            assert_eq!(value.0, 1);
+            assert_eq!(value.1, 2);
+            assert!((value.2 - 0.1).abs() < 1e-9);
+            assert!((value.3 - 9.0).abs() < 1e-9);
+            // The callers sub-mapping keeps the same (cc, nc, tt, ct) shape.
+            let caller_val: (i64, i64, f64, f64) =
+                value.4.get_item(("caller.py", 9, "parent")).unwrap().unwrap().extract().unwrap();
+            assert_eq!(caller_val.0, 1);
+        });
+    }
+}
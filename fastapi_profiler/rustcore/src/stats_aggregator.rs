@@ -0,0 +1,211 @@
+//! Per-endpoint aggregation of request latencies.
+//!
+//! [`PyAggregatedStats`] is the object the Python middleware feeds completed
+//! requests into. It keeps a mergeable latency distribution (a t-digest) so
+//! that p50/p90/p95/p99 can be recovered in bounded memory and folded across
+//! worker processes.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::openmetrics::{self, EndpointStats};
+use crate::pstats_export::{self, FuncKey, FuncRecord, SortKey};
+use crate::tdigest::TDigest;
+use crate::window::SlidingWindow;
+
+/// Aggregated latency statistics, recorded sample by sample.
+///
+/// The digest is mergeable, so each worker in a multi-process deployment can
+/// keep its own instance and the parent can [`merge`](PyAggregatedStats::merge)
+/// them into a single global view without shipping raw samples around.
+#[pyclass]
+pub struct PyAggregatedStats {
+    digest: TDigest,
+    /// Per-function call data, in the shape `pstats` expects.
+    funcs: HashMap<FuncKey, FuncRecord>,
+    /// Per-endpoint latency and request counters, keyed by `(method, path)`.
+    endpoints: HashMap<(String, String), EndpointStats>,
+    /// Recent-activity ring buffer powering the live dashboard queries.
+    window: SlidingWindow,
+}
+
+/// Current wall-clock time in unix seconds.
+fn now_seconds() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+#[pymethods]
+impl PyAggregatedStats {
+    #[new]
+    #[pyo3(signature = (compression = 100.0))]
+    fn new(compression: f64) -> Self {
+        PyAggregatedStats {
+            digest: TDigest::new(compression),
+            funcs: HashMap::new(),
+            endpoints: HashMap::new(),
+            window: SlidingWindow::new(60, 1.0),
+        }
+    }
+
+    /// Record one request latency (in seconds).
+    fn record(&mut self, latency: f64) {
+        self.digest.record(latency);
+    }
+
+    /// Record a completed request against its endpoint: updates the per-endpoint
+    /// latency digest, count/sum and status breakdown, the global digest, and
+    /// the sliding window.
+    ///
+    /// `now` is the observation time in unix seconds; pass `None` to use the
+    /// current wall clock (tests pass an explicit value for determinism).
+    #[pyo3(signature = (method, path, status, latency, now = None))]
+    fn observe(&mut self, method: &str, path: &str, status: u16, latency: f64, now: Option<f64>) {
+        self.digest.record(latency);
+        let entry = self
+            .endpoints
+            .entry((method.to_owned(), path.to_owned()))
+            .or_default();
+        entry.count += 1;
+        entry.sum += latency;
+        entry.digest.record(latency);
+        *entry.status_counts.entry(status).or_insert(0) += 1;
+        self.window.record(now.unwrap_or_else(now_seconds), latency);
+    }
+
+    /// Summarise activity over the last `seconds` for a live dashboard: counts,
+    /// throughput and tail latencies folded from the ring buffer on demand.
+    #[pyo3(signature = (seconds, now = None))]
+    fn window_stats<'py>(&self, py: Python<'py>, seconds: f64, now: Option<f64>) -> &'py PyDict {
+        let now = now.unwrap_or_else(now_seconds);
+        let stats = self.window.window_stats(now, seconds);
+        let dict = PyDict::new(py);
+        let _ = dict.set_item("count", stats.count);
+        let _ = dict.set_item("sum", stats.sum);
+        let _ = dict.set_item("min", if stats.count > 0 { stats.min } else { 0.0 });
+        let _ = dict.set_item("max", if stats.count > 0 { stats.max } else { 0.0 });
+        let _ = dict.set_item("rps", stats.count as f64 / stats.span_seconds);
+        let _ = dict.set_item("p50", stats.digest.quantile(0.5));
+        let _ = dict.set_item("p90", stats.digest.quantile(0.9));
+        let _ = dict.set_item("p95", stats.digest.quantile(0.95));
+        let _ = dict.set_item("p99", stats.digest.quantile(0.99));
+        dict
+    }
+
+    /// Requests per second averaged over the last `seconds`.
+    #[pyo3(signature = (seconds, now = None))]
+    fn rate(&self, seconds: f64, now: Option<f64>) -> f64 {
+        self.window.rate(now.unwrap_or_else(now_seconds), seconds)
+    }
+
+    /// Render the aggregated endpoint stats as Prometheus/OpenMetrics text
+    /// exposition format, suitable for serving from a `/metrics` endpoint.
+    fn to_openmetrics(&self) -> String {
+        openmetrics::render(&self.endpoints)
+    }
+
+    /// Estimate the latency at quantile `q` (in `[0, 1]`).
+    fn quantile(&self, q: f64) -> f64 {
+        self.digest.quantile(q)
+    }
+
+    /// Number of recorded samples.
+    fn count(&self) -> f64 {
+        self.digest.len()
+    }
+
+    /// Fold another instance's distribution into this one.
+    fn merge(&mut self, other: &PyAggregatedStats) {
+        self.digest.merge(&other.digest);
+        for (key, rec) in &other.funcs {
+            let entry = self.funcs.entry(key.clone()).or_default();
+            entry.cc += rec.cc;
+            entry.nc += rec.nc;
+            entry.tt += rec.tt;
+            entry.ct += rec.ct;
+            for (ck, &(cc, nc, tt, ct)) in &rec.callers {
+                let c = entry.callers.entry(ck.clone()).or_insert((0, 0, 0.0, 0.0));
+                c.0 += cc;
+                c.1 += nc;
+                c.2 += tt;
+                c.3 += ct;
+            }
+        }
+        for (key, ep) in &other.endpoints {
+            let entry = self.endpoints.entry(key.clone()).or_default();
+            entry.count += ep.count;
+            entry.sum += ep.sum;
+            entry.digest.merge(&ep.digest);
+            for (status, count) in &ep.status_counts {
+                *entry.status_counts.entry(*status).or_insert(0) += count;
+            }
+        }
+    }
+
+    /// Record a single function call for `pstats`-style export.
+    ///
+    /// `caller` is the `(filename, lineno, funcname)` of the calling frame, if
+    /// any; passing it in lets the exported profile reconstruct the call graph.
+    #[pyo3(signature = (filename, lineno, funcname, tt, ct, caller = None))]
+    fn record_call(
+        &mut self,
+        filename: String,
+        lineno: i64,
+        funcname: String,
+        tt: f64,
+        ct: f64,
+        caller: Option<(String, i64, String)>,
+    ) {
+        let key: FuncKey = (filename, lineno, funcname);
+        let entry = self.funcs.entry(key).or_default();
+        entry.cc += 1;
+        entry.nc += 1;
+        entry.tt += tt;
+        entry.ct += ct;
+        if let Some(ck) = caller {
+            let c = entry.callers.entry(ck).or_insert((0, 0, 0.0, 0.0));
+            c.0 += 1;
+            c.1 += 1;
+            c.2 += tt;
+            c.3 += ct;
+        }
+    }
+
+    /// Return the collected per-call data as a sorted textual table, mirroring
+    /// `pstats.Stats.sort_stats(...).print_stats(restrict)`.
+    #[pyo3(signature = (sort_by = "cumulative", restrict = None))]
+    fn sorted_table(&self, sort_by: &str, restrict: Option<usize>) -> PyResult<String> {
+        let sort = SortKey::parse(sort_by).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(format!("unknown sort column: {sort_by}"))
+        })?;
+        Ok(pstats_export::format_table(&self.funcs, sort, restrict))
+    }
+
+    /// Write one `.prof` file for a request into `directory`, naming it via the
+    /// `filename_format` template (`{method}`, `{path}`, `{elapsed}`, `{time}`).
+    ///
+    /// Returns the full path written.
+    #[pyo3(signature = (directory, method, path, elapsed, time, filename_format = "{method}_{path}_{time}.prof"))]
+    fn write_prof(
+        &self,
+        py: Python<'_>,
+        directory: &str,
+        method: &str,
+        path: &str,
+        elapsed: f64,
+        time: f64,
+        filename_format: &str,
+    ) -> PyResult<String> {
+        let name = pstats_export::expand_filename(filename_format, method, path, elapsed, time);
+        let full = Path::new(directory).join(name);
+        let bytes = pstats_export::marshal_stats(py, &self.funcs)?;
+        std::fs::write(&full, bytes.as_bytes())?;
+        Ok(full.to_string_lossy().into_owned())
+    }
+}
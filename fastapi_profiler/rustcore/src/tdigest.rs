@@ -0,0 +1,256 @@
+//! A mergeable, memory-bounded approximate quantile estimator.
+//!
+//! This is a fairly direct implementation of Dunning's t-digest: the
+//! distribution is summarised by a list of centroids `(mean, count)` kept
+//! sorted by `mean`. Centroids near the tails are allowed to hold only a few
+//! samples while those in the middle may absorb many, which is what gives the
+//! structure its high accuracy at extreme quantiles for a fixed memory budget.
+//!
+//! The size of each centroid is governed by a scale function that maps a
+//! cumulative quantile `q` onto a "k-index":
+//!
+//! ```text
+//! k(q) = (delta / 2pi) * arcsin(2q - 1)
+//! ```
+//!
+//! Two adjacent centroids may be merged only while the span they cover stays
+//! within one unit of `k`, i.e. `k(q_right) - k(q_left) <= 1`. Because `merge`
+//! just concatenates two centroid lists and re-compresses, per-worker digests
+//! fold into a global one without re-sending raw samples.
+
+use std::f64::consts::PI;
+
+/// A weighted point summarising a cluster of ingested values.
+#[derive(Clone, Copy, Debug)]
+pub struct Centroid {
+    pub mean: f64,
+    pub count: f64,
+}
+
+/// A t-digest over `f64` samples.
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    centroids: Vec<Centroid>,
+    count: f64,
+    compression: f64,
+    /// Unmerged values waiting to be folded into `centroids`.
+    buffer: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        TDigest::new(100.0)
+    }
+}
+
+impl TDigest {
+    /// Create an empty digest with the given compression parameter `delta`.
+    ///
+    /// Larger values keep more centroids and trade memory for accuracy; 100 is
+    /// the usual default and bounds the digest to a few hundred centroids.
+    pub fn new(compression: f64) -> Self {
+        TDigest {
+            centroids: Vec::new(),
+            count: 0.0,
+            compression: compression.max(20.0),
+            buffer: Vec::new(),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Total number of samples recorded.
+    pub fn len(&self) -> f64 {
+        self.count + self.buffer.len() as f64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0.0
+    }
+
+    /// Record a single value.
+    pub fn record(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.buffer.push(value);
+        // Flush lazily: buffering keeps the hot path cheap and lets a batch of
+        // values be folded in one sorted pass.
+        if self.buffer.len() as f64 >= self.compression {
+            self.compress();
+        }
+    }
+
+    /// Fold `other` into `self` by concatenating centroid lists and
+    /// re-compressing. This is the operation that lets a multi-process
+    /// deployment combine per-worker digests into a single distribution.
+    pub fn merge(&mut self, other: &TDigest) {
+        if other.is_empty() {
+            return;
+        }
+        self.flush_buffer();
+        let mut other = other.clone();
+        other.flush_buffer();
+        self.centroids.extend_from_slice(&other.centroids);
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.compress();
+    }
+
+    /// The scale function `k(q) = (delta / 2pi) * arcsin(2q - 1)`.
+    fn k(&self, q: f64) -> f64 {
+        let q = q.clamp(0.0, 1.0);
+        (self.compression / (2.0 * PI)) * (2.0 * q - 1.0).asin()
+    }
+
+    /// Promote any buffered values into singleton centroids without clustering.
+    fn flush_buffer(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        for &v in &self.buffer {
+            self.centroids.push(Centroid {
+                mean: v,
+                count: 1.0,
+            });
+            self.count += 1.0;
+            self.min = self.min.min(v);
+            self.max = self.max.max(v);
+        }
+        self.buffer.clear();
+    }
+
+    /// Re-cluster the centroid list in a single sorted pass, merging adjacent
+    /// centroids while the quantile span they cover stays within one `k` unit.
+    fn compress(&mut self) {
+        self.flush_buffer();
+        if self.centroids.len() <= 1 {
+            return;
+        }
+
+        self.centroids
+            .sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap_or(std::cmp::Ordering::Equal));
+
+        let total = self.count;
+        let mut merged: Vec<Centroid> = Vec::with_capacity(self.centroids.len());
+        let mut current = self.centroids[0];
+        // Cumulative count up to (but not including) `current`.
+        let mut cum = 0.0;
+
+        for &next in &self.centroids[1..] {
+            let q_left = cum / total;
+            let proposed = current.count + next.count;
+            let q_right = (cum + proposed) / total;
+            if self.k(q_right) - self.k(q_left) <= 1.0 {
+                // Absorb `next` into the current centroid.
+                let weight = current.count + next.count;
+                current.mean = (current.mean * current.count + next.mean * next.count) / weight;
+                current.count = weight;
+            } else {
+                cum += current.count;
+                merged.push(current);
+                current = next;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// Estimate the value at quantile `q` (in `[0, 1]`) by walking the centroids
+    /// and interpolating between their cumulative ranks.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let mut digest = self.clone();
+        digest.compress();
+
+        if digest.centroids.is_empty() {
+            return f64::NAN;
+        }
+        if digest.centroids.len() == 1 {
+            return digest.centroids[0].mean;
+        }
+
+        let q = q.clamp(0.0, 1.0);
+        let target = q * digest.count;
+
+        // Left tail: anything below the first centroid's centre maps to min.
+        if target <= digest.centroids[0].count / 2.0 {
+            return digest.min;
+        }
+        // Right tail: symmetric treatment against max.
+        let last = digest.centroids.len() - 1;
+        if target >= digest.count - digest.centroids[last].count / 2.0 {
+            return digest.max;
+        }
+
+        // Interpolate between consecutive centroid centres. Each centroid's
+        // centre sits at its cumulative-count midpoint.
+        let mut cum = 0.0;
+        for i in 0..last {
+            let c = digest.centroids[i];
+            let n = digest.centroids[i + 1];
+            let c_center = cum + c.count / 2.0;
+            let n_center = cum + c.count + n.count / 2.0;
+            if target < n_center {
+                let frac = (target - c_center) / (n_center - c_center);
+                return c.mean + frac * (n.mean - c.mean);
+            }
+            cum += c.count;
+        }
+        digest.centroids[last].mean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantiles_of_uniform_distribution_are_accurate() {
+        let mut d = TDigest::new(100.0);
+        for i in 0..=1000 {
+            d.record(i as f64);
+        }
+        // A compression-100 digest is accurate to a small percentage of the
+        // range; 15 out of 1000 is comfortably within that envelope and tighter
+        // at the tails by construction.
+        assert!((d.quantile(0.5) - 500.0).abs() < 15.0, "p50 = {}", d.quantile(0.5));
+        assert!((d.quantile(0.9) - 900.0).abs() < 15.0, "p90 = {}", d.quantile(0.9));
+        assert!((d.quantile(0.99) - 990.0).abs() < 15.0, "p99 = {}", d.quantile(0.99));
+    }
+
+    #[test]
+    fn merge_matches_recording_all_samples_into_one() {
+        let mut a = TDigest::new(100.0);
+        let mut b = TDigest::new(100.0);
+        let mut combined = TDigest::new(100.0);
+        for i in 0..1000 {
+            let v = i as f64;
+            combined.record(v);
+            if i % 2 == 0 {
+                a.record(v);
+            } else {
+                b.record(v);
+            }
+        }
+        a.merge(&b);
+
+        assert_eq!(a.len(), combined.len());
+        for q in [0.1, 0.5, 0.9, 0.99] {
+            let diff = (a.quantile(q) - combined.quantile(q)).abs();
+            assert!(diff < 15.0, "q{q}: merged={} combined={}", a.quantile(q), combined.quantile(q));
+        }
+    }
+
+    #[test]
+    fn empty_merge_is_a_noop() {
+        let mut a = TDigest::new(100.0);
+        a.record(1.0);
+        a.record(2.0);
+        let before = a.len();
+        a.merge(&TDigest::new(100.0));
+        assert_eq!(a.len(), before);
+    }
+}
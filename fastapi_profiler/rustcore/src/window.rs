@@ -0,0 +1,189 @@
+//! Fixed-memory sliding-window aggregation for live dashboards.
+//!
+//! Accumulating over all time hides recent regressions behind the historical
+//! average. This structure keeps a ring of fixed-duration buckets (by default
+//! 60 one-second buckets); each bucket owns its own count/sum/min/max and a
+//! small mergeable [`TDigest`]. A query folds the buckets overlapping the
+//! requested window on demand, and buckets that fall off the back are recycled
+//! in place — so memory is constant regardless of uptime.
+
+use crate::tdigest::TDigest;
+
+/// One bucket of the ring, covering a single `bucket_seconds`-wide slice.
+struct Bucket {
+    /// Which absolute bucket-index this slot currently holds; used to detect a
+    /// stale slot that must be recycled before reuse.
+    epoch: u64,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    digest: TDigest,
+}
+
+impl Bucket {
+    fn empty() -> Self {
+        Bucket {
+            epoch: u64::MAX,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            digest: TDigest::new(100.0),
+        }
+    }
+
+    /// Reset the slot so it can represent a fresh `epoch`.
+    fn recycle(&mut self, epoch: u64) {
+        self.epoch = epoch;
+        self.count = 0;
+        self.sum = 0.0;
+        self.min = f64::INFINITY;
+        self.max = f64::NEG_INFINITY;
+        self.digest = TDigest::new(100.0);
+    }
+}
+
+/// A merged view over some span of buckets.
+#[derive(Default)]
+pub struct WindowStats {
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    pub digest: TDigest,
+    /// Width of the folded window, in seconds.
+    pub span_seconds: f64,
+}
+
+/// A ring buffer of time buckets.
+pub struct SlidingWindow {
+    buckets: Vec<Bucket>,
+    bucket_seconds: f64,
+}
+
+impl SlidingWindow {
+    /// Create a window of `num_buckets` slots each `bucket_seconds` wide.
+    pub fn new(num_buckets: usize, bucket_seconds: f64) -> Self {
+        let mut buckets = Vec::with_capacity(num_buckets.max(1));
+        for _ in 0..num_buckets.max(1) {
+            buckets.push(Bucket::empty());
+        }
+        SlidingWindow {
+            buckets,
+            bucket_seconds: if bucket_seconds > 0.0 { bucket_seconds } else { 1.0 },
+        }
+    }
+
+    fn epoch_of(&self, now: f64) -> u64 {
+        (now / self.bucket_seconds).floor().max(0.0) as u64
+    }
+
+    /// Record `value` observed at wall-clock time `now` (unix seconds).
+    pub fn record(&mut self, now: f64, value: f64) {
+        let epoch = self.epoch_of(now);
+        let slot = (epoch as usize) % self.buckets.len();
+        let bucket = &mut self.buckets[slot];
+        if bucket.epoch != epoch {
+            bucket.recycle(epoch);
+        }
+        bucket.count += 1;
+        bucket.sum += value;
+        bucket.min = bucket.min.min(value);
+        bucket.max = bucket.max.max(value);
+        bucket.digest.record(value);
+    }
+
+    /// Fold the buckets covering the last `seconds` into a single view.
+    ///
+    /// The window is clamped to what the ring can actually hold, so asking for
+    /// more than `num_buckets * bucket_seconds` simply folds everything.
+    pub fn window_stats(&self, now: f64, seconds: f64) -> WindowStats {
+        let current = self.epoch_of(now);
+        let max_span = self.buckets.len() as u64;
+        let wanted = (seconds / self.bucket_seconds).ceil().max(1.0) as u64;
+        let span = wanted.min(max_span);
+        let oldest = current.saturating_sub(span - 1);
+
+        let mut out = WindowStats {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            span_seconds: span as f64 * self.bucket_seconds,
+            ..WindowStats::default()
+        };
+        for bucket in &self.buckets {
+            if bucket.count == 0 || bucket.epoch < oldest || bucket.epoch > current {
+                continue;
+            }
+            out.count += bucket.count;
+            out.sum += bucket.sum;
+            out.min = out.min.min(bucket.min);
+            out.max = out.max.max(bucket.max);
+            out.digest.merge(&bucket.digest);
+        }
+        out
+    }
+
+    /// Requests per second averaged over the last `seconds`.
+    pub fn rate(&self, now: f64, seconds: f64) -> f64 {
+        let stats = self.window_stats(now, seconds);
+        if stats.span_seconds > 0.0 {
+            stats.count as f64 / stats.span_seconds
+        } else {
+            0.0
+        }
+    }
+
+    #[cfg(test)]
+    fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_folds_only_the_requested_span() {
+        let mut w = SlidingWindow::new(60, 1.0);
+        // One request per second for 10 seconds, at t = 0..10.
+        for t in 0..10 {
+            w.record(t as f64, 1.0);
+        }
+        // The last 3 seconds (epochs 7, 8, 9) hold three requests.
+        let last3 = w.window_stats(9.0, 3.0);
+        assert_eq!(last3.count, 3);
+        assert_eq!(w.rate(9.0, 3.0), 1.0);
+    }
+
+    #[test]
+    fn window_clamps_to_ring_capacity() {
+        let mut w = SlidingWindow::new(10, 1.0);
+        for t in 0..10 {
+            w.record(t as f64, 1.0);
+        }
+        // Asking for 1000s can only fold what the 10-bucket ring retains.
+        let stats = w.window_stats(9.0, 1000.0);
+        assert_eq!(stats.count, 10);
+        assert_eq!(stats.span_seconds, 10.0);
+    }
+
+    #[test]
+    fn stale_buckets_are_recycled_after_a_full_wrap() {
+        let mut w = SlidingWindow::new(10, 1.0);
+        // Record across 100 epochs — ten full wraps of the ring.
+        for t in 0..100 {
+            w.record(t as f64, 1.0);
+        }
+        // Bucket count is fixed regardless of uptime.
+        assert_eq!(w.bucket_count(), 10);
+        // Only the final 10 epochs survive; older ones were recycled in place.
+        let stats = w.window_stats(99.0, 10.0);
+        assert_eq!(stats.count, 10);
+        // A window ending at a later time sees only buckets still within range,
+        // not the stale contents the slots used to hold.
+        let future = w.window_stats(200.0, 10.0);
+        assert_eq!(future.count, 0);
+    }
+}